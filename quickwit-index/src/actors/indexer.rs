@@ -18,22 +18,36 @@
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::HashMap;
 use std::io;
 use std::ops::RangeInclusive;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
 use std::sync::Arc;
+use std::sync::OnceLock;
 use std::time::Duration;
 use std::time::Instant;
 
 use anyhow::Context;
 use quickwit_actors::Actor;
+use quickwit_actors::ActorContext;
 use quickwit_actors::Mailbox;
+use quickwit_actors::MessageProcessError;
 use quickwit_actors::SendError;
 use quickwit_actors::SyncActor;
+use quickwit_index_config::DocParsingError;
 use quickwit_index_config::IndexConfig;
 use tantivy::schema::Field;
 use tantivy::Document;
+use time::format_description::well_known::Iso8601;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::Date;
+use time::OffsetDateTime;
+use time::PrimitiveDateTime;
 use tracing::warn;
 
 use crate::models::IndexedSplit;
@@ -43,6 +57,63 @@ use crate::models::RawDocBatch;
 pub struct IndexerCounters {
     parse_error: u64,
     docs: u64,
+    // Documents dropped because the dead-letter sink was full or closed.
+    dead_letter_dropped: u64,
+    // Documents with a timestamp field present whose text value didn't match any supported
+    // ISO-8601 variant.
+    timestamp_unparseable: u64,
+}
+
+// Thresholds controlling when an `IndexedSplit` is committed: whichever is reached first.
+#[derive(Clone, Debug)]
+pub struct CommitPolicy {
+    pub timeout: Duration,
+    pub max_num_bytes: u64,
+    pub max_num_docs: u64,
+}
+
+impl Default for CommitPolicy {
+    fn default() -> CommitPolicy {
+        CommitPolicy {
+            timeout: Duration::from_secs(60),
+            max_num_bytes: 100_000_000,
+            max_num_docs: 10_000_000,
+        }
+    }
+}
+
+// A document `IndexConfig::doc_from_json` failed to parse, sent to the dead-letter sink.
+#[derive(Debug)]
+pub struct DeadLetter {
+    pub doc_json: String,
+    pub parsing_error: DocParsingError,
+    pub offset: u64,
+}
+
+// Unit used to interpret a numeric timestamp field value.
+#[derive(Clone, Copy, Debug)]
+pub enum TimestampUnit {
+    Seconds,
+    Millis,
+    Micros,
+}
+
+impl TimestampUnit {
+    fn to_canonical_seconds(self, raw_timestamp: i64) -> i64 {
+        match self {
+            TimestampUnit::Seconds => raw_timestamp,
+            // `div_euclid` floors instead of truncating toward zero, so pre-1970 values round
+            // the right way.
+            TimestampUnit::Millis => raw_timestamp.div_euclid(1_000),
+            TimestampUnit::Micros => raw_timestamp.div_euclid(1_000_000),
+        }
+    }
+}
+
+impl Default for TimestampUnit {
+    fn default() -> TimestampUnit {
+        TimestampUnit::Seconds
+    }
 }
 
 enum ScratchDirectory {
@@ -63,21 +134,157 @@ impl ScratchDirectory {
     }
 }
 
+// Message handled by `Indexer`: a regular batch, or a self-scheduled commit timeout.
+pub enum IndexerMessage {
+    Batch(RawDocBatch),
+    CommitTimeout,
+}
+
+impl From<RawDocBatch> for IndexerMessage {
+    fn from(batch: RawDocBatch) -> IndexerMessage {
+        IndexerMessage::Batch(batch)
+    }
+}
+
+// Identifies one `Indexer`'s registration with the shared commit-timer service.
+type TimerRegistrationId = u64;
+
+// Request sent to the shared commit-timer service.
+enum TimerRequest {
+    Arm {
+        indexer_id: TimerRegistrationId,
+        deadline: Instant,
+        mailbox: Mailbox<IndexerMessage>,
+    },
+    Cancel {
+        indexer_id: TimerRegistrationId,
+    },
+    Unregister {
+        indexer_id: TimerRegistrationId,
+    },
+}
+
+// How long the timer service should block before re-checking the earliest armed deadline.
+fn time_until_deadline(deadline_opt: Option<Instant>, now: Instant) -> Option<Duration> {
+    deadline_opt.map(|deadline| deadline.saturating_duration_since(now))
+}
+
+struct ArmedTimer {
+    deadline: Instant,
+    mailbox: Mailbox<IndexerMessage>,
+}
+
+static COMMIT_TIMER_SENDER: OnceLock<mpsc::Sender<TimerRequest>> = OnceLock::new();
+static NEXT_TIMER_REGISTRATION_ID: AtomicU64 = AtomicU64::new(0);
+
+// Sender for the one commit-timer thread shared by every `Indexer` in the process, spawned
+// lazily on first use.
+fn commit_timer_sender() -> mpsc::Sender<TimerRequest> {
+    COMMIT_TIMER_SENDER
+        .get_or_init(|| {
+            let (timer_tx, timer_rx) = mpsc::channel::<TimerRequest>();
+            std::thread::spawn(move || run_commit_timer_service(timer_rx));
+            timer_tx
+        })
+        .clone()
+}
+
+// Body of the shared commit-timer thread.
+fn run_commit_timer_service(timer_rx: mpsc::Receiver<TimerRequest>) {
+    let mut armed: HashMap<TimerRegistrationId, ArmedTimer> = HashMap::new();
+    loop {
+        let next_deadline = armed.values().map(|timer| timer.deadline).min();
+        let recv_result = match time_until_deadline(next_deadline, Instant::now()) {
+            Some(timeout) => timer_rx.recv_timeout(timeout),
+            None => timer_rx
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match recv_result {
+            Ok(TimerRequest::Arm {
+                indexer_id,
+                deadline,
+                mailbox,
+            }) => {
+                armed.insert(indexer_id, ArmedTimer { deadline, mailbox });
+            }
+            Ok(TimerRequest::Cancel { indexer_id }) | Ok(TimerRequest::Unregister { indexer_id }) => {
+                armed.remove(&indexer_id);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let now = Instant::now();
+                let elapsed_ids: Vec<TimerRegistrationId> = armed
+                    .iter()
+                    .filter(|(_, timer)| timer.deadline <= now)
+                    .map(|(indexer_id, _)| *indexer_id)
+                    .collect();
+                for indexer_id in elapsed_ids {
+                    if let Some(timer) = armed.remove(&indexer_id) {
+                        // Non-blocking: this thread is shared by every `Indexer` in the process,
+                        // so one backed-up mailbox must not stall `CommitTimeout` delivery to the
+                        // rest. A dropped timeout isn't fatal: the indexer's own next batch still
+                        // re-checks `commit_deadline_elapsed`.
+                        if let Err(send_error) =
+                            timer.mailbox.try_send(IndexerMessage::CommitTimeout)
+                        {
+                            warn!(err=?send_error, "dropping CommitTimeout: indexer mailbox unavailable");
+                        }
+                    }
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+// Parameters for `Indexer::try_new`.
+pub struct IndexerParams {
+    pub index_id: String,
+    pub index_config: Arc<dyn IndexConfig>,
+    //< if None, we create a tempdirectory.
+    pub indexing_directory: Option<PathBuf>,
+    pub commit_policy: CommitPolicy,
+    pub timestamp_unit: TimestampUnit,
+    pub sink: Mailbox<IndexedSplit>,
+    pub self_mailbox: Mailbox<IndexerMessage>,
+    pub dead_letter_sink_opt: Option<Mailbox<DeadLetter>>,
+}
+
 pub struct Indexer {
     index_id: String,
     index_config: Arc<dyn IndexConfig>,
     // splits index writer will write in TempDir within this directory
     indexing_scratch_directory: ScratchDirectory,
-    commit_timeout: Duration,
+    commit_policy: CommitPolicy,
     sink: Mailbox<IndexedSplit>,
+    // Handed to the commit-timer service on each `Arm` request so it can post back to us.
+    self_mailbox: Mailbox<IndexerMessage>,
+    // Handle to the commit-timer service shared by every `Indexer` in the process.
+    timer_tx: mpsc::Sender<TimerRequest>,
+    // This indexer's registration with the shared commit-timer service.
+    timer_registration_id: TimerRegistrationId,
+    dead_letter_sink_opt: Option<Mailbox<DeadLetter>>,
     next_commit_deadline_opt: Option<Instant>,
     current_split_opt: Option<IndexedSplit>,
     counters: IndexerCounters,
     timestamp_field_opt: Option<Field>,
+    timestamp_unit: TimestampUnit,
+    // Number of documents seen so far, kept across batches; the next document's `DeadLetter`
+    // offset is this plus one.
+    num_docs_seen: u64,
+}
+
+impl Drop for Indexer {
+    // Unregisters this indexer from the shared commit-timer service.
+    fn drop(&mut self) {
+        let _ = self.timer_tx.send(TimerRequest::Unregister {
+            indexer_id: self.timer_registration_id,
+        });
+    }
 }
 
 impl Actor for Indexer {
-    type Message = RawDocBatch;
+    type Message = IndexerMessage;
 
     type ObservableState = IndexerCounters;
 
@@ -86,86 +293,238 @@ impl Actor for Indexer {
     }
 }
 
-fn extract_timestamp(doc: &Document, timestamp_field_opt: Option<Field>) -> Option<i64> {
-    let timestamp_field = timestamp_field_opt?;
-    let timestamp_value = doc.get_first(timestamp_field)?;
-    timestamp_value.i64_value()
+fn exceeds_commit_policy(size_in_bytes: u64, num_docs: u64, commit_policy: &CommitPolicy) -> bool {
+    size_in_bytes >= commit_policy.max_num_bytes || num_docs >= commit_policy.max_num_docs
+}
+
+// Whether the scheduled commit deadline has actually elapsed.
+fn commit_deadline_elapsed(next_commit_deadline_opt: Option<Instant>, now: Instant) -> bool {
+    next_commit_deadline_opt
+        .map(|deadline| now >= deadline)
+        .unwrap_or(false)
+}
+
+// Text didn't match any of the ISO-8601 variants `parse_timestamp_text` knows how to read.
+#[derive(Debug)]
+struct TimestampParseError;
+
+// Parses a timestamp string against the ISO-8601 variants operators actually emit in logs.
+fn parse_timestamp_text(timestamp_str: &str) -> Result<OffsetDateTime, TimestampParseError> {
+    if let Ok(datetime) = OffsetDateTime::parse(timestamp_str, &Rfc3339) {
+        return Ok(datetime);
+    }
+    if let Ok(datetime) = OffsetDateTime::parse(timestamp_str, &Iso8601::DEFAULT) {
+        return Ok(datetime);
+    }
+    let space_separated = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+    if let Ok(datetime) = PrimitiveDateTime::parse(timestamp_str, &space_separated) {
+        return Ok(datetime.assume_utc());
+    }
+    let basic_no_separator = format_description!("[year][month][day]T[hour][minute][second]");
+    if let Ok(datetime) = PrimitiveDateTime::parse(timestamp_str, &basic_no_separator) {
+        return Ok(datetime.assume_utc());
+    }
+    let date_only = format_description!("[year]-[month]-[day]");
+    if let Ok(date) = Date::parse(timestamp_str, &date_only) {
+        return Ok(date.midnight().assume_utc());
+    }
+    Err(TimestampParseError)
+}
+
+// Reads the timestamp field as i64, u64, date, or ISO-8601 text, normalized to seconds.
+fn extract_timestamp(
+    doc: &Document,
+    timestamp_field_opt: Option<Field>,
+    timestamp_unit: TimestampUnit,
+) -> Result<Option<i64>, TimestampParseError> {
+    let timestamp_field = match timestamp_field_opt {
+        Some(timestamp_field) => timestamp_field,
+        None => return Ok(None),
+    };
+    let timestamp_value = match doc.get_first(timestamp_field) {
+        Some(timestamp_value) => timestamp_value,
+        None => return Ok(None),
+    };
+    if let Some(raw_timestamp) = timestamp_value.i64_value() {
+        return Ok(Some(timestamp_unit.to_canonical_seconds(raw_timestamp)));
+    }
+    if let Some(raw_timestamp) = timestamp_value.u64_value() {
+        return Ok(Some(timestamp_unit.to_canonical_seconds(raw_timestamp as i64)));
+    }
+    if let Some(date) = timestamp_value.date_value() {
+        return Ok(Some(date.unix_timestamp()));
+    }
+    if let Some(timestamp_str) = timestamp_value.text() {
+        let datetime = parse_timestamp_text(timestamp_str)?;
+        return Ok(Some(datetime.unix_timestamp()));
+    }
+    Ok(None)
+}
+
+// Forwards a document that failed `doc_from_json` to the dead-letter sink. Returns `false` only
+// when a sink is configured but dropped the document.
+fn forward_to_dead_letter_sink(
+    dead_letter_sink_opt: Option<&Mailbox<DeadLetter>>,
+    doc_json: String,
+    parsing_error: DocParsingError,
+    offset: u64,
+) -> bool {
+    let dead_letter_sink = match dead_letter_sink_opt {
+        Some(dead_letter_sink) => dead_letter_sink,
+        None => return true,
+    };
+    let dead_letter = DeadLetter {
+        doc_json,
+        parsing_error,
+        offset,
+    };
+    match dead_letter_sink.try_send(dead_letter) {
+        Ok(()) => true,
+        Err(send_error) => {
+            warn!(err=?send_error, "dropping document: dead-letter sink is unavailable");
+            false
+        }
+    }
 }
 
 impl SyncActor for Indexer {
     fn process_message(
         &mut self,
-        batch: RawDocBatch,
-        _context: quickwit_actors::ActorContext<'_, Self::Message>,
-    ) -> Result<(), quickwit_actors::MessageProcessError> {
+        message: IndexerMessage,
+        _context: ActorContext<'_, Self::Message>,
+    ) -> Result<(), MessageProcessError> {
+        match message {
+            IndexerMessage::Batch(batch) => self.process_batch(batch),
+            IndexerMessage::CommitTimeout => self.process_commit_timeout(),
+        }
+    }
+}
+
+impl Indexer {
+    fn process_batch(&mut self, batch: RawDocBatch) -> Result<(), MessageProcessError> {
+        // Fallback for a `CommitTimeout` the shared commit-timer thread dropped (it sends
+        // non-blocking): any later message still catches an overdue split instead of it sitting
+        // forever.
+        if commit_deadline_elapsed(self.next_commit_deadline_opt, Instant::now()) {
+            self.send_to_packager()?;
+        }
         let index_config = self.index_config.clone();
         let timestamp_field_opt = self.timestamp_field_opt;
-        let indexed_split = self.indexed_split()?;
+        let timestamp_unit = self.timestamp_unit;
+        let commit_policy = self.commit_policy.clone();
+        let dead_letter_sink_opt = self.dead_letter_sink_opt.clone();
+        let mut num_parse_errors = 0u64;
+        let mut num_dead_letter_dropped = 0u64;
+        let mut num_timestamp_unparseable = 0u64;
+        let mut offset = self.num_docs_seen;
         for doc_json in batch.docs {
+            offset += 1;
+            let indexed_split = self.indexed_split()?;
             indexed_split.size_in_bytes += doc_json.len() as u64;
             let doc_parsing_result = index_config.doc_from_json(&doc_json);
-            let doc = match doc_parsing_result {
-                Ok(doc) => doc,
-                Err(doc_parsing_error) => {
-                    // TODO we should at least keep track of the number of parse error.
-                    warn!(err=?doc_parsing_error);
-                    continue;
+            match doc_parsing_result {
+                Ok(doc) => {
+                    match extract_timestamp(&doc, timestamp_field_opt, timestamp_unit) {
+                        Ok(Some(timestamp)) => {
+                            let new_timestamp_range = match indexed_split.time_range.as_ref() {
+                                Some(range) => RangeInclusive::new(
+                                    timestamp.min(*range.start()),
+                                    timestamp.max(*range.end()),
+                                ),
+                                None => RangeInclusive::new(timestamp, timestamp),
+                            };
+                            indexed_split.time_range = Some(new_timestamp_range);
+                        }
+                        Ok(None) => {}
+                        Err(TimestampParseError) => {
+                            warn!(
+                                "document timestamp field did not match any supported ISO-8601 format"
+                            );
+                            num_timestamp_unparseable += 1;
+                        }
+                    }
+                    indexed_split.num_docs += 1;
+                    indexed_split.index_writer.add_document(doc);
+                }
+                Err(parsing_error) => {
+                    warn!(err=?parsing_error);
+                    num_parse_errors += 1;
+                    if !forward_to_dead_letter_sink(
+                        dead_letter_sink_opt.as_ref(),
+                        doc_json,
+                        parsing_error,
+                        offset,
+                    ) {
+                        num_dead_letter_dropped += 1;
+                    }
                 }
-            };
-            if let Some(timestamp) = extract_timestamp(&doc, timestamp_field_opt) {
-                let new_timestamp_range = match indexed_split.time_range.as_ref() {
-                    Some(range) => RangeInclusive::new(
-                        timestamp.min(*range.start()),
-                        timestamp.max(*range.end()),
-                    ),
-                    None => RangeInclusive::new(timestamp, timestamp),
-                };
-                indexed_split.time_range = Some(new_timestamp_range);
             }
-            indexed_split.index_writer.add_document(doc);
-        }
 
-        // TODO this approach of deadline is not correct, as it never triggers if no need
-        // new message arrives.
-        // We do need to implement timeout message in actors to get the right behavior.
-        if let Some(deadline) = self.next_commit_deadline_opt {
-            let now = Instant::now();
-            if now >= deadline {
+            // Re-check after every document, parsed or not: `size_in_bytes` grows either way, so
+            // a sustained stream of malformed documents must still trip `max_num_bytes` instead of
+            // growing the split unbounded.
+            let indexed_split = self.indexed_split()?;
+            if exceeds_commit_policy(
+                indexed_split.size_in_bytes,
+                indexed_split.num_docs,
+                &commit_policy,
+            ) {
                 self.send_to_packager()?;
             }
-        } else {
-            self.next_commit_deadline_opt = None;
         }
+        self.counters.parse_error += num_parse_errors;
+        self.counters.dead_letter_dropped += num_dead_letter_dropped;
+        self.counters.timestamp_unparseable += num_timestamp_unparseable;
+        self.num_docs_seen = offset;
         Ok(())
     }
-}
 
-impl Indexer {
-    // TODO take all of the parameter and dispatch them in index config, or in a different
-    // IndexerParams object.
-    pub fn try_new(
-        index_id: String,
-        index_config: Arc<dyn IndexConfig>,
-        indexing_directory: Option<PathBuf>, //< if None, we create a tempdirectory.
-        commit_timeout: Duration,
-        sink: Mailbox<IndexedSplit>,
-    ) -> anyhow::Result<Indexer> {
+    // Commits the current split if the deadline that armed it has actually elapsed.
+    fn process_commit_timeout(&mut self) -> Result<(), MessageProcessError> {
+        if self.current_split_opt.is_none() {
+            return Ok(());
+        }
+        if commit_deadline_elapsed(self.next_commit_deadline_opt, Instant::now()) {
+            self.send_to_packager()?;
+        }
+        Ok(())
+    }
+
+    pub fn try_new(params: IndexerParams) -> anyhow::Result<Indexer> {
+        let IndexerParams {
+            index_id,
+            index_config,
+            indexing_directory,
+            commit_policy,
+            timestamp_unit,
+            sink,
+            self_mailbox,
+            dead_letter_sink_opt,
+        } = params;
         let indexing_scratch_directory = if let Some(path) = indexing_directory {
             ScratchDirectory::Path(path)
         } else {
             ScratchDirectory::try_new_temp()?
         };
         let time_field_opt = index_config.timestamp_field();
+        let timer_tx = commit_timer_sender();
+        let timer_registration_id = NEXT_TIMER_REGISTRATION_ID.fetch_add(1, Ordering::Relaxed);
         Ok(Indexer {
             index_id,
             index_config,
-            commit_timeout,
+            commit_policy,
             sink,
+            self_mailbox,
+            timer_tx,
+            timer_registration_id,
+            dead_letter_sink_opt,
             next_commit_deadline_opt: None,
             counters: IndexerCounters::default(),
             current_split_opt: None,
             indexing_scratch_directory,
             timestamp_field_opt: time_field_opt,
+            timestamp_unit,
+            num_docs_seen: 0,
         })
     }
 
@@ -183,7 +542,13 @@ impl Indexer {
         if self.current_split_opt.is_none() {
             let new_indexed_split = self.create_indexed_split()?;
             self.current_split_opt = Some(new_indexed_split);
-            self.next_commit_deadline_opt = Some(Instant::now() + self.commit_timeout);
+            let deadline = Instant::now() + self.commit_policy.timeout;
+            self.next_commit_deadline_opt = Some(deadline);
+            let _ = self.timer_tx.send(TimerRequest::Arm {
+                indexer_id: self.timer_registration_id,
+                deadline,
+                mailbox: self.self_mailbox.clone(),
+            });
         }
         let current_index_split = self.current_split_opt.as_mut().with_context(|| {
             "No index writer available. Please report: this should never happen."
@@ -197,7 +562,318 @@ impl Indexer {
         } else {
             return Ok(());
         };
+        self.next_commit_deadline_opt = None;
+        let _ = self.timer_tx.send(TimerRequest::Cancel {
+            indexer_id: self.timer_registration_id,
+        });
         self.sink.send_blocking(indexed_split)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_actors::create_test_mailbox;
+    use quickwit_index_config::DocParsingError;
+    use tantivy::schema::Schema;
+    use tantivy::schema::STORED;
+    use tantivy::doc;
+
+    use super::*;
+
+    #[test]
+    fn test_timestamp_unit_to_canonical_seconds() {
+        assert_eq!(TimestampUnit::Seconds.to_canonical_seconds(42), 42);
+        assert_eq!(TimestampUnit::Millis.to_canonical_seconds(42_000), 42);
+        assert_eq!(TimestampUnit::Micros.to_canonical_seconds(42_000_000), 42);
+    }
+
+    #[test]
+    fn test_timestamp_unit_to_canonical_seconds_negative_floors() {
+        // -1500ms is 1.5s before the epoch, so it must floor to -2s, not truncate to -1s.
+        assert_eq!(TimestampUnit::Millis.to_canonical_seconds(-1_500), -2);
+        assert_eq!(TimestampUnit::Micros.to_canonical_seconds(-1_500_000), -2);
+    }
+
+    #[test]
+    fn test_extract_timestamp_no_field() {
+        let doc = Document::default();
+        assert_eq!(
+            extract_timestamp(&doc, None, TimestampUnit::Seconds).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extract_timestamp_i64_field() {
+        let mut schema_builder = Schema::builder();
+        let timestamp_field = schema_builder.add_i64_field("ts", STORED);
+        schema_builder.build();
+        let doc = doc!(timestamp_field => 1_600_000_000i64);
+        assert_eq!(
+            extract_timestamp(&doc, Some(timestamp_field), TimestampUnit::Seconds).unwrap(),
+            Some(1_600_000_000)
+        );
+    }
+
+    #[test]
+    fn test_extract_timestamp_u64_millis_field() {
+        let mut schema_builder = Schema::builder();
+        let timestamp_field = schema_builder.add_u64_field("ts", STORED);
+        schema_builder.build();
+        let doc = doc!(timestamp_field => 1_600_000_000_000u64);
+        assert_eq!(
+            extract_timestamp(&doc, Some(timestamp_field), TimestampUnit::Millis).unwrap(),
+            Some(1_600_000_000)
+        );
+    }
+
+    #[test]
+    fn test_extract_timestamp_rfc3339_text_field() {
+        let mut schema_builder = Schema::builder();
+        let timestamp_field = schema_builder.add_text_field("ts", STORED);
+        schema_builder.build();
+        let doc = doc!(timestamp_field => "2020-09-13T12:26:40Z");
+        assert_eq!(
+            extract_timestamp(&doc, Some(timestamp_field), TimestampUnit::Seconds).unwrap(),
+            Some(1_600_000_000)
+        );
+    }
+
+    #[test]
+    fn test_extract_timestamp_date_only_text_field() {
+        let mut schema_builder = Schema::builder();
+        let timestamp_field = schema_builder.add_text_field("ts", STORED);
+        schema_builder.build();
+        let doc = doc!(timestamp_field => "2020-09-13");
+        assert_eq!(
+            extract_timestamp(&doc, Some(timestamp_field), TimestampUnit::Seconds).unwrap(),
+            Some(1_600_000_000)
+        );
+    }
+
+    #[test]
+    fn test_extract_timestamp_space_separated_text_field() {
+        let mut schema_builder = Schema::builder();
+        let timestamp_field = schema_builder.add_text_field("ts", STORED);
+        schema_builder.build();
+        let doc = doc!(timestamp_field => "2020-09-13 12:26:40");
+        assert_eq!(
+            extract_timestamp(&doc, Some(timestamp_field), TimestampUnit::Seconds).unwrap(),
+            Some(1_600_000_000)
+        );
+    }
+
+    #[test]
+    fn test_extract_timestamp_basic_no_separator_text_field() {
+        let mut schema_builder = Schema::builder();
+        let timestamp_field = schema_builder.add_text_field("ts", STORED);
+        schema_builder.build();
+        let doc = doc!(timestamp_field => "20200913T122640");
+        assert_eq!(
+            extract_timestamp(&doc, Some(timestamp_field), TimestampUnit::Seconds).unwrap(),
+            Some(1_600_000_000)
+        );
+    }
+
+    #[test]
+    fn test_extract_timestamp_unparseable_text_field() {
+        let mut schema_builder = Schema::builder();
+        let timestamp_field = schema_builder.add_text_field("ts", STORED);
+        schema_builder.build();
+        let doc = doc!(timestamp_field => "not a timestamp");
+        assert!(extract_timestamp(&doc, Some(timestamp_field), TimestampUnit::Seconds).is_err());
+    }
+
+    #[test]
+    fn test_exceeds_commit_policy_under_thresholds() {
+        let commit_policy = CommitPolicy {
+            timeout: Duration::from_secs(60),
+            max_num_bytes: 1_000,
+            max_num_docs: 100,
+        };
+        assert!(!exceeds_commit_policy(999, 99, &commit_policy));
+    }
+
+    #[test]
+    fn test_exceeds_commit_policy_max_num_bytes() {
+        let commit_policy = CommitPolicy {
+            timeout: Duration::from_secs(60),
+            max_num_bytes: 1_000,
+            max_num_docs: 100,
+        };
+        assert!(exceeds_commit_policy(1_000, 0, &commit_policy));
+        assert!(exceeds_commit_policy(1_001, 0, &commit_policy));
+    }
+
+    #[test]
+    fn test_exceeds_commit_policy_max_num_docs() {
+        let commit_policy = CommitPolicy {
+            timeout: Duration::from_secs(60),
+            max_num_bytes: 1_000,
+            max_num_docs: 100,
+        };
+        assert!(exceeds_commit_policy(0, 100, &commit_policy));
+        assert!(exceeds_commit_policy(0, 101, &commit_policy));
+    }
+
+    #[test]
+    fn test_time_until_deadline_none_when_unarmed() {
+        assert_eq!(time_until_deadline(None, Instant::now()), None);
+    }
+
+    #[test]
+    fn test_time_until_deadline_future() {
+        let now = Instant::now();
+        let deadline = now + Duration::from_secs(5);
+        assert_eq!(
+            time_until_deadline(Some(deadline), now),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn test_time_until_deadline_past_saturates_to_zero() {
+        let now = Instant::now();
+        let deadline = now - Duration::from_secs(5);
+        assert_eq!(time_until_deadline(Some(deadline), now), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_commit_deadline_elapsed_no_deadline_armed() {
+        assert!(!commit_deadline_elapsed(None, Instant::now()));
+    }
+
+    #[test]
+    fn test_commit_deadline_elapsed_in_the_future() {
+        let now = Instant::now();
+        assert!(!commit_deadline_elapsed(
+            Some(now + Duration::from_secs(60)),
+            now
+        ));
+    }
+
+    #[test]
+    fn test_commit_deadline_elapsed_in_the_past() {
+        let now = Instant::now();
+        assert!(commit_deadline_elapsed(
+            Some(now - Duration::from_secs(1)),
+            now
+        ));
+    }
+
+    #[test]
+    fn test_forward_to_dead_letter_sink_no_sink_configured() {
+        let forwarded = forward_to_dead_letter_sink(
+            None,
+            "{".to_string(),
+            DocParsingError::NotJson("invalid".to_string()),
+            1,
+        );
+        assert!(forwarded);
+    }
+
+    #[test]
+    fn test_forward_to_dead_letter_sink_delivers_with_offset() {
+        let (dead_letter_sink, inbox) = create_test_mailbox();
+        let forwarded = forward_to_dead_letter_sink(
+            Some(&dead_letter_sink),
+            "{".to_string(),
+            DocParsingError::NotJson("invalid".to_string()),
+            7,
+        );
+        assert!(forwarded);
+
+        let dead_letter = inbox
+            .recv_blocking()
+            .expect("dead-letter sink should have received the forwarded document");
+        assert_eq!(dead_letter.doc_json, "{");
+        assert!(matches!(dead_letter.parsing_error, DocParsingError::NotJson(_)));
+        assert_eq!(dead_letter.offset, 7);
+    }
+
+    #[test]
+    fn test_forward_to_dead_letter_sink_drops_when_sink_closed() {
+        let (dead_letter_sink, inbox) = create_test_mailbox();
+        drop(inbox);
+        let forwarded = forward_to_dead_letter_sink(
+            Some(&dead_letter_sink),
+            "{".to_string(),
+            DocParsingError::NotJson("invalid".to_string()),
+            1,
+        );
+        assert!(!forwarded);
+    }
+
+    // Minimal `IndexConfig` for driving a real `Indexer` end-to-end in tests.
+    struct TestIndexConfig {
+        schema: Schema,
+    }
+
+    impl IndexConfig for TestIndexConfig {
+        fn schema(&self) -> Schema {
+            self.schema.clone()
+        }
+
+        fn timestamp_field(&self) -> Option<Field> {
+            None
+        }
+
+        fn doc_from_json(&self, doc_json: &str) -> Result<Document, DocParsingError> {
+            self.schema
+                .parse_document(doc_json)
+                .map_err(|err| DocParsingError::NotJson(err.to_string()))
+        }
+    }
+
+    fn test_indexer_params(
+        commit_policy: CommitPolicy,
+        self_mailbox: Mailbox<IndexerMessage>,
+        sink: Mailbox<IndexedSplit>,
+    ) -> IndexerParams {
+        IndexerParams {
+            index_id: "test-index".to_string(),
+            index_config: Arc::new(TestIndexConfig {
+                schema: Schema::builder().build(),
+            }),
+            indexing_directory: None,
+            commit_policy,
+            timestamp_unit: TimestampUnit::Seconds,
+            sink,
+            self_mailbox,
+            dead_letter_sink_opt: None,
+        }
+    }
+
+    #[test]
+    fn test_commit_timeout_flushes_idle_split_via_shared_timer() {
+        let (self_mailbox, self_inbox) = create_test_mailbox();
+        let (sink, sink_inbox) = create_test_mailbox();
+        let commit_policy = CommitPolicy {
+            timeout: Duration::from_millis(20),
+            max_num_bytes: u64::MAX,
+            max_num_docs: u64::MAX,
+        };
+        let mut indexer =
+            Indexer::try_new(test_indexer_params(commit_policy, self_mailbox, sink)).unwrap();
+        indexer
+            .process_batch(RawDocBatch {
+                docs: vec!["{}".to_string()],
+            })
+            .unwrap();
+
+        // No further batch arrives: only the shared commit-timer thread can deliver this, proving
+        // the timer thread -> mailbox wiring actually works, not just the deadline arithmetic.
+        let timed_out_message = self_inbox
+            .recv_blocking()
+            .expect("commit-timer thread should deliver a CommitTimeout once idle");
+        assert!(matches!(timed_out_message, IndexerMessage::CommitTimeout));
+
+        indexer.process_commit_timeout().unwrap();
+
+        let flushed_split = sink_inbox
+            .recv_blocking()
+            .expect("idle split should have reached the packager sink");
+        assert_eq!(flushed_split.num_docs, 1);
+    }
+}